@@ -11,12 +11,22 @@
 //! ```
 
 
-use clap::{App, Arg, AppSettings};
+use clap::{App, Arg, AppSettings, Error, ErrorKind};
 use self::super::Algorithm;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::fs;
 
+mod config;
+mod merkle;
+mod pool;
+mod manifest;
+
+pub use self::merkle::{MerkleTree, Proof, verify as verify_merkle_proof};
+pub use self::pool::hash_all;
+pub use self::manifest::{Format, Entry};
+
 
 /// Representation of the application's all configurable values.
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -27,8 +37,27 @@ pub struct Options {
     pub algorithm: Algorithm,
     /// Whether to verify or create checksums. Default: yes
     pub verify: bool,
-    /// Max recursion depth. Default: `LastLevel`
-    pub depth: DepthSetting,
+    /// Recursion behaviour, deduced from `--depth`/`--no-recurse`/`--tree`.
+    /// Default: `DirAction::Flat(DepthSetting::LastLevel)`
+    pub action: DirAction,
+    /// Follow symlinked directories while recursing. Default: `false`
+    pub follow_symlinks: bool,
+    /// Fold all file hashes into a single Merkle root instead of emitting
+    /// one hash per file. Default: `false`
+    pub merkle: bool,
+    /// Emit a compact inclusion proof for this path instead of the full
+    /// tree. Requires `merkle`. Default: `None`
+    pub prove: Option<PathBuf>,
+    /// Check a previously-emitted inclusion proof against a published
+    /// root instead of hashing anything. Default: `None`
+    pub verify_proof: Option<ProofCheck>,
+    /// Number of worker threads hashing files concurrently.
+    /// `1` hashes sequentially on the calling thread. Default: logical CPU count
+    pub jobs: u32,
+    /// On-disk manifest layout to read/write. Default: `Format::Native`
+    pub format: Format,
+    /// Write the manifest here instead of stdout. Default: `None` (stdout)
+    pub output: Option<PathBuf>,
 }
 
 /// Representation of how deep recursion should be.
@@ -44,10 +73,69 @@ pub enum DepthSetting {
     NRemaining(u32),
 }
 
+/// What the directory descent should do, deduced from `--depth`,
+/// `--no-recurse`, and `--tree`. Modeled on exa's `DirAction`: rather
+/// than letting independent flags silently combine (or conflict) at
+/// use time, `Options::parse` resolves them into one of these up front.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum DirAction {
+    /// Recurse per the given depth, emitting one digest per file.
+    Flat(DepthSetting),
+    /// Recurse per the given depth, additionally printing the directory
+    /// hierarchy alongside each digest.
+    Tree(DepthSetting),
+}
+
+impl DirAction {
+    /// The recursion depth this action carries, regardless of variant.
+    pub fn depth(&self) -> DepthSetting {
+        match *self {
+            DirAction::Flat(depth) | DirAction::Tree(depth) => depth,
+        }
+    }
+}
+
+/// The inputs needed to check a previously-emitted inclusion proof
+/// against a published Merkle root, as requested by `--verify-proof`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct ProofCheck {
+    /// Path to the proof file written out by `--prove`.
+    pub proof_file: PathBuf,
+    /// Published root to check the proof against, as a hex digest.
+    pub root: String,
+}
+
+/// Tracks which canonicalized directories have already been descended
+/// into while following symlinks, so a cyclic symlink can't recurse
+/// forever.
+///
+/// The set is threaded through the descent by the caller: before
+/// recursing into a symlinked directory, canonicalize it and call
+/// `visit()`; a `false` result means it's already been walked, so the
+/// caller should skip it and warn instead of recursing again.
+#[derive(Debug, Default)]
+pub struct VisitedDirs(HashSet<PathBuf>);
+
+impl VisitedDirs {
+    /// An empty visited set, to seed the top of a descent.
+    pub fn new() -> Self {
+        VisitedDirs(HashSet::new())
+    }
+
+    /// Record `dir` (already canonicalized by the caller) as visited.
+    /// Returns `true` the first time a given path is seen, `false` on
+    /// every subsequent (cyclic) visit.
+    pub fn visit(&mut self, dir: PathBuf) -> bool {
+        self.0.insert(dir)
+    }
+}
+
 
 impl Options {
     /// Parse `env`-wide command-line arguments into an `Options` instance
     pub fn parse() -> Options {
+        let default_jobs = num_cpus::get().to_string();
+
         let matches = App::new("checksums")
             .setting(AppSettings::AllowLeadingHyphen)
             .setting(AppSettings::ColoredHelp)
@@ -64,18 +152,95 @@ impl Options {
                     Arg::from_usage("--verify -v 'Verify checksums (default)'").overrides_with("create"),
                     Arg::from_usage("--depth=[depth] -d 'Max recursion depth. `-1` for infinite.'")
                         .default_value("0")
-                        .validator(Options::depth_validator)
-                        .overrides_with("create")])
+                        .validator(Options::depth_validator),
+                    Arg::from_usage("--no-recurse 'Do not recurse into subdirectories. {n}\
+                                     Equivalent to --depth 0, and conflicts with --depth.'")
+                        .next_line_help(true),
+                    Arg::from_usage("--follow-symlinks 'Follow symlinked directories while recursing'"),
+                    Arg::from_usage("--tree 'Print the directory hierarchy alongside digests'"),
+                    Arg::from_usage("--config=[FILE] 'Read defaults from an INI-style config file. {n}\
+                                     Command-line arguments override values it sets.'")
+                        .next_line_help(true),
+                    Arg::from_usage("--merkle 'Fold all file hashes into a single Merkle root {n}\
+                                     instead of one hash per file'")
+                        .next_line_help(true),
+                    Arg::from_usage("--prove=[PATH] 'Emit a compact inclusion proof for PATH {n}\
+                                     against the Merkle root. Requires --merkle'")
+                        .next_line_help(true)
+                        .requires("merkle"),
+                    Arg::from_usage("--verify-proof 'Check a proof emitted by --prove against {n}\
+                                     a published root. Requires --proof-file and --root'")
+                        .next_line_help(true)
+                        .conflicts_with("prove")
+                        .requires_all(&["proof-file", "root"]),
+                    Arg::from_usage("--proof-file=[FILE] 'Proof to check, as written out by --prove. {n}\
+                                     Requires --verify-proof'")
+                        .next_line_help(true)
+                        .requires("verify-proof"),
+                    Arg::from_usage("--root=[DIGEST] 'Published Merkle root to check --proof-file against. {n}\
+                                     Requires --verify-proof'")
+                        .next_line_help(true)
+                        .requires("verify-proof"),
+                    Arg::from_usage("--jobs=[N] -j 'Number of worker threads hashing files {n}\
+                                     concurrently. Default: logical CPU count'")
+                        .next_line_help(true)
+                        .default_value(&default_jobs)
+                        .validator(Options::jobs_validator),
+                    Arg::from_usage("--format=[format] -f 'On-disk manifest layout to read/write. {n}\
+                                     One of: native, gnu, bsd'")
+                        .next_line_help(true)
+                        .default_value("native")
+                        .validator(Options::format_validator),
+                    Arg::from_usage("--output=[FILE] -o 'Write the manifest here instead of stdout'")])
             .get_matches();
 
+        let config = matches.value_of("config")
+            .map(|f| config::load(Path::new(f)).unwrap_or_else(|e| panic!("failed to read config file: {}", e)))
+            .unwrap_or_else(config::Config::new);
+
         Options {
-            dir: fs::canonicalize(matches.value_of("DIRECTORY").unwrap()).unwrap(),
-            algorithm: Algorithm::from_str(matches.value_of("algorithm").unwrap()).unwrap(),
-            verify: !matches.is_present("create"),
-            depth: DepthSetting::from_str(matches.value_of("depth").unwrap()).unwrap(),
+            dir: Options::resolve_dir(matches.occurrences_of("DIRECTORY") > 0, matches.value_of("DIRECTORY").unwrap(), &config),
+            algorithm: Options::resolve_algorithm(matches.occurrences_of("algorithm") > 0, matches.value_of("algorithm").unwrap(), &config),
+            verify: if matches.occurrences_of("create") > 0 || matches.occurrences_of("verify") > 0 {
+                !matches.is_present("create")
+            } else {
+                config.get("verify").map(|v| v == "true").unwrap_or(true)
+            },
+            action: Options::deduce_action(matches.is_present("no-recurse"),
+                                            matches.occurrences_of("depth") > 0,
+                                            matches.is_present("follow-symlinks"),
+                                            matches.is_present("tree"),
+                                            if matches.occurrences_of("depth") > 0 {
+                                                matches.value_of("depth").unwrap()
+                                            } else {
+                                                config.get("depth").map(String::as_str).unwrap_or_else(|| matches.value_of("depth").unwrap())
+                                            })
+                .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::ValueValidation).exit()),
+            follow_symlinks: matches.is_present("follow-symlinks"),
+            merkle: matches.is_present("merkle"),
+            prove: matches.value_of("prove").map(PathBuf::from),
+            verify_proof: if matches.is_present("verify-proof") {
+                Some(ProofCheck {
+                    proof_file: PathBuf::from(matches.value_of("proof-file").unwrap()),
+                    root: matches.value_of("root").unwrap().to_string(),
+                })
+            } else {
+                None
+            },
+            jobs: u32::from_str(matches.value_of("jobs").unwrap()).unwrap(),
+            format: Format::from_str(matches.value_of("format").unwrap()).unwrap(),
+            output: matches.value_of("output").map(PathBuf::from),
         }
     }
 
+    fn jobs_validator(s: String) -> Result<(), String> {
+        u32::from_str(&s).map_err(|e| e.to_string()).and_then(|n| if n == 0 { Err("--jobs must be at least 1".to_string()) } else { Ok(()) })
+    }
+
+    fn format_validator(s: String) -> Result<(), String> {
+        Format::from_str(&s).map(|_| ())
+    }
+
     fn algorithm_validator(s: String) -> Result<(), String> {
         Algorithm::from_str(&s).map(|_| ())
     }
@@ -93,6 +258,49 @@ impl Options {
     fn depth_validator(s: String) -> Result<(), String> {
         DepthSetting::from_str(&s).map(|_| ())
     }
+
+    /// Resolve the effective `DIRECTORY` value (CLI takes precedence over
+    /// `config`) and canonicalize it, running it through the same
+    /// `directory_validator` the CLI value already passed so a bad `dir =`
+    /// line in a config file gets the same clean clap-style error as a bad
+    /// `DIRECTORY` argument instead of an `unwrap` panic.
+    fn resolve_dir(cli_explicit: bool, cli_value: &str, config: &config::Config) -> PathBuf {
+        let raw = if cli_explicit { cli_value } else { config.get("dir").map(String::as_str).unwrap_or(cli_value) };
+
+        Options::directory_validator(raw.to_string()).unwrap_or_else(|e| Error::with_description(&e, ErrorKind::ValueValidation).exit());
+        fs::canonicalize(raw).unwrap()
+    }
+
+    /// Resolve the effective `--algorithm` value (CLI takes precedence over
+    /// `config`), running it through the same `algorithm_validator` the CLI
+    /// value already passed so a bad `algorithm =` line in a config file
+    /// gets the same clean clap-style error as a bad `--algorithm` argument.
+    fn resolve_algorithm(cli_explicit: bool, cli_value: &str, config: &config::Config) -> Algorithm {
+        let raw = if cli_explicit { cli_value } else { config.get("algorithm").map(String::as_str).unwrap_or(cli_value) };
+
+        Options::algorithm_validator(raw.to_string()).unwrap_or_else(|e| Error::with_description(&e, ErrorKind::ValueValidation).exit());
+        Algorithm::from_str(raw).unwrap()
+    }
+
+    /// Deduce the `DirAction` implied by `--depth`/`--no-recurse`/`--tree`,
+    /// rejecting incoherent combinations instead of silently picking one.
+    ///
+    /// Takes the already-extracted flag state rather than `&ArgMatches` so
+    /// the conflict/derivation rules can be unit-tested without building a
+    /// real `ArgMatches`.
+    fn deduce_action(no_recurse: bool, depth_explicit: bool, follow_symlinks: bool, tree: bool, depth_value: &str) -> Result<DirAction, String> {
+        if no_recurse && depth_explicit {
+            return Err("--depth conflicts with --no-recurse".to_string());
+        }
+
+        let depth = if no_recurse { DepthSetting::LastLevel } else { DepthSetting::from_str(depth_value)? };
+
+        if follow_symlinks && !depth.can_recurse() {
+            return Err("--follow-symlinks has no effect without recursion".to_string());
+        }
+
+        Ok(if tree { DirAction::Tree(depth) } else { DirAction::Flat(depth) })
+    }
 }
 
 
@@ -240,4 +448,73 @@ mod tests {
             }
         }
     }
+
+    mod dir_action {
+        use self::super::super::{DepthSetting, DirAction, Options};
+
+        #[test]
+        fn plain_depth_is_flat() {
+            let action = Options::deduce_action(false, false, false, false, "2").unwrap();
+            assert_eq!(action, DirAction::Flat(DepthSetting::NRemaining(2)));
+        }
+
+        #[test]
+        fn tree_flag_wraps_in_tree_variant() {
+            let action = Options::deduce_action(false, false, false, true, "0").unwrap();
+            assert_eq!(action, DirAction::Tree(DepthSetting::LastLevel));
+        }
+
+        #[test]
+        fn no_recurse_forces_last_level() {
+            let action = Options::deduce_action(true, false, false, false, "0").unwrap();
+            assert_eq!(action, DirAction::Flat(DepthSetting::LastLevel));
+        }
+
+        #[test]
+        fn no_recurse_with_explicit_depth_conflicts() {
+            Options::deduce_action(true, true, false, false, "5").unwrap_err();
+        }
+
+        #[test]
+        fn follow_symlinks_without_recursion_is_rejected() {
+            // no_recurse forces LastLevel, which can't recurse.
+            Options::deduce_action(true, false, true, false, "0").unwrap_err();
+        }
+
+        #[test]
+        fn follow_symlinks_with_recursion_is_allowed() {
+            let action = Options::deduce_action(false, false, true, false, "-1").unwrap();
+            assert_eq!(action, DirAction::Flat(DepthSetting::Infinite));
+        }
+
+        #[test]
+        fn bad_depth_value_is_an_error() {
+            Options::deduce_action(false, true, false, false, "not-a-number").unwrap_err();
+        }
+    }
+
+    mod visited_dirs {
+        use self::super::super::VisitedDirs;
+        use std::path::PathBuf;
+
+        #[test]
+        fn first_visit_is_new() {
+            let mut visited = VisitedDirs::new();
+            assert!(visited.visit(PathBuf::from("/a")));
+        }
+
+        #[test]
+        fn repeat_visit_is_not_new() {
+            let mut visited = VisitedDirs::new();
+            assert!(visited.visit(PathBuf::from("/a")));
+            assert!(!visited.visit(PathBuf::from("/a")));
+        }
+
+        #[test]
+        fn distinct_paths_are_independent() {
+            let mut visited = VisitedDirs::new();
+            assert!(visited.visit(PathBuf::from("/a")));
+            assert!(visited.visit(PathBuf::from("/b")));
+        }
+    }
 }
@@ -0,0 +1,247 @@
+//! Layered, Mercurial-style INI configuration files.
+//!
+//! A config file is a sequence of `[section]` headers (accepted but not
+//! semantically meaningful -- keys are flattened across sections) and
+//! `key = value` items; a line beginning with whitespace continues the
+//! previous value, and a line starting with `;` or `#` is a comment.
+//!
+//! Two directives are recognised:
+//!
+//! * `%include <path>` merges another file, resolved relative to the
+//!   including file's directory, depth-first at the point it appears.
+//! * `%unset <key>` removes any value a previous layer assigned to
+//!   `key`, so a later layer (or the built-in default) takes over.
+//!
+//! Layers are folded in file order -- includes before the lines that
+//! follow them -- and the last non-unset value for a key wins.
+
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+
+
+/// Recognised configuration keys, mapped onto `Options` fields.
+pub const KEYS: &[&str] = &["dir", "algorithm", "verify", "depth"];
+
+/// The folded result of a config file and everything it `%include`s:
+/// the final value for each key that was ever set and not subsequently
+/// `%unset`.
+pub type Config = HashMap<String, String>;
+
+
+/// A single contribution from a config file: either assign a key, or
+/// clear whatever an earlier layer assigned to it.
+#[derive(Debug, Clone)]
+enum Event {
+    Set(String, String),
+    Unset(String),
+}
+
+
+/// Load `path`, recursively resolving `%include`s, and fold the result
+/// into a single `Config`.
+pub fn load(path: &Path) -> Result<Config, String> {
+    let mut include_path = Vec::new();
+    let mut config = Config::new();
+    for event in parse_file(path, &mut include_path)? {
+        match event {
+            Event::Set(key, value) => {
+                config.insert(key, value);
+            }
+            Event::Unset(key) => {
+                config.remove(&key);
+            }
+        }
+    }
+    Ok(config)
+}
+
+/// Parse a single file into its ordered list of events, inlining
+/// `%include`d files depth-first at the point they occur.
+///
+/// `include_path` is the stack of files currently being parsed, from the
+/// top-level file down to `path` -- not every file ever seen. A file
+/// that's already an ancestor on that stack would recurse forever (a
+/// direct or mutual include cycle) and is rejected; a file included
+/// more than once from unrelated branches (e.g. two sibling configs
+/// both `%include`ing a shared `common.conf`) is ordinary and allowed.
+fn parse_file(path: &Path, include_path: &mut Vec<PathBuf>) -> Result<Vec<Event>, String> {
+    let canonical = fs::canonicalize(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if include_path.contains(&canonical) {
+        return Err(format!("{}: include cycle detected (already on the current include path)", path.display()));
+    }
+
+    include_path.push(canonical.clone());
+    let result = parse_file_contents(&canonical, path, include_path);
+    include_path.pop();
+    result
+}
+
+fn parse_file_contents(canonical: &Path, original: &Path, include_path: &mut Vec<PathBuf>) -> Result<Vec<Event>, String> {
+    let contents = fs::read_to_string(canonical).map_err(|e| format!("{}: {}", original.display(), e))?;
+    let base_dir = canonical.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut events = Vec::new();
+    for raw_line in contents.lines() {
+        let trimmed = raw_line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if raw_line.starts_with(' ') || raw_line.starts_with('\t') {
+            match events.last_mut() {
+                Some(&mut Event::Set(_, ref mut value)) => {
+                    value.push(' ');
+                    value.push_str(trimmed);
+                }
+                _ => return Err(format!("{}: continuation line without a preceding key: {:?}", original.display(), raw_line)),
+            }
+            continue;
+        }
+
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            continue;
+        }
+
+        if trimmed.starts_with("%include") {
+            let target = base_dir.join(trimmed["%include".len()..].trim());
+            events.extend(parse_file(&target, include_path)?);
+            continue;
+        }
+
+        if trimmed.starts_with("%unset") {
+            events.push(Event::Unset(trimmed["%unset".len()..].trim().to_string()));
+            continue;
+        }
+
+        match trimmed.find('=') {
+            Some(eq) => events.push(Event::Set(trimmed[..eq].trim().to_string(), trimmed[eq + 1..].trim().to_string())),
+            None => return Err(format!("{}: malformed config line: {:?}", original.display(), trimmed)),
+        }
+    }
+
+    Ok(events)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Event;
+    use std::path::PathBuf;
+    use std::fs;
+
+    /// A fresh, empty directory under the system temp dir, scoped to one
+    /// test by name and PID so parallel test runs don't collide.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("checksums-config-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn load_parses_a_real_file() {
+        let dir = scratch_dir("load_parses_a_real_file");
+        let path = dir.join("checksums.conf");
+        fs::write(&path, "[core]\n; a comment\nalgorithm = MD5\ndepth = 2\n").unwrap();
+
+        let config = super::load(&path).unwrap();
+        assert_eq!(config.get("algorithm").map(String::as_str), Some("MD5"));
+        assert_eq!(config.get("depth").map(String::as_str), Some("2"));
+    }
+
+    #[test]
+    fn continuation_line_is_appended_to_previous_value() {
+        let dir = scratch_dir("continuation_line_is_appended_to_previous_value");
+        let path = dir.join("checksums.conf");
+        fs::write(&path, "dir = /a/first\n  /a/second\n").unwrap();
+
+        let config = super::load(&path).unwrap();
+        assert_eq!(config.get("dir").map(String::as_str), Some("/a/first /a/second"));
+    }
+
+    #[test]
+    fn unset_directive_clears_an_earlier_value() {
+        let dir = scratch_dir("unset_directive_clears_an_earlier_value");
+        let path = dir.join("checksums.conf");
+        fs::write(&path, "algorithm = MD5\n%unset algorithm\n").unwrap();
+
+        let config = super::load(&path).unwrap();
+        assert!(!config.contains_key("algorithm"));
+    }
+
+    #[test]
+    fn include_is_merged_depth_first_and_can_be_overridden() {
+        let dir = scratch_dir("include_is_merged_depth_first_and_can_be_overridden");
+        fs::write(dir.join("common.conf"), "algorithm = MD5\nverify = true\n").unwrap();
+        fs::write(dir.join("checksums.conf"), "%include common.conf\nalgorithm = SHA1\n").unwrap();
+
+        let config = super::load(&dir.join("checksums.conf")).unwrap();
+        // The including file's own later line overrode the included default.
+        assert_eq!(config.get("algorithm").map(String::as_str), Some("SHA1"));
+        // ...but values the including file never touches still come through.
+        assert_eq!(config.get("verify").map(String::as_str), Some("true"));
+    }
+
+    #[test]
+    fn mutually_including_files_error_instead_of_recursing_forever() {
+        let dir = scratch_dir("mutually_including_files_error_instead_of_recursing_forever");
+        fs::write(dir.join("a.conf"), "%include b.conf\n").unwrap();
+        fs::write(dir.join("b.conf"), "%include a.conf\n").unwrap();
+
+        super::load(&dir.join("a.conf")).unwrap_err();
+    }
+
+    #[test]
+    fn diamond_inclusion_of_a_shared_file_is_not_a_cycle() {
+        // a.conf and b.conf both %include the same common.conf; common.conf
+        // itself includes nothing back, so this isn't a cycle and should load.
+        let dir = scratch_dir("diamond_inclusion_of_a_shared_file_is_not_a_cycle");
+        fs::write(dir.join("common.conf"), "algorithm = MD5\n").unwrap();
+        fs::write(dir.join("a.conf"), "%include common.conf\n").unwrap();
+        fs::write(dir.join("b.conf"), "%include common.conf\n").unwrap();
+        fs::write(dir.join("checksums.conf"), "%include a.conf\n%include b.conf\n").unwrap();
+
+        let config = super::load(&dir.join("checksums.conf")).unwrap();
+        assert_eq!(config.get("algorithm").map(String::as_str), Some("MD5"));
+    }
+
+    #[test]
+    fn fold_set_then_unset_falls_back() {
+        let events = vec![Event::Set("algorithm".to_string(), "MD5".to_string()), Event::Unset("algorithm".to_string())];
+
+        let mut config = super::Config::new();
+        for event in events {
+            match event {
+                Event::Set(k, v) => {
+                    config.insert(k, v);
+                }
+                Event::Unset(k) => {
+                    config.remove(&k);
+                }
+            }
+        }
+
+        assert!(!config.contains_key("algorithm"));
+    }
+
+    #[test]
+    fn fold_last_value_wins() {
+        let events = vec![Event::Set("depth".to_string(), "1".to_string()), Event::Set("depth".to_string(), "2".to_string())];
+
+        let mut config = super::Config::new();
+        for event in events {
+            match event {
+                Event::Set(k, v) => {
+                    config.insert(k, v);
+                }
+                Event::Unset(k) => {
+                    config.remove(&k);
+                }
+            }
+        }
+
+        assert_eq!(config.get("depth").map(String::as_str), Some("2"));
+    }
+}
@@ -0,0 +1,161 @@
+//! Reading and writing checksum manifests in the two coreutils-compatible
+//! layouts, so `checksums` can interoperate with `sha1sum`/`sha256sum`/
+//! `md5sum` and friends.
+//!
+//! * `Format::Gnu` is the `<hexdigest>␠␠<path>` layout `*sum` writes
+//!   (two spaces, or a space and a `*` for binary mode): the algorithm
+//!   isn't recorded in the line, so it's taken from the configured
+//!   `Algorithm` instead.
+//! * `Format::Bsd` is the `ALGO (path) = digest` layout `*sum --tag`
+//!   (and the BSD `*sum` tools) write: the algorithm tag is read back
+//!   off each line instead of being assumed.
+
+
+use std::str::FromStr;
+
+
+/// Which on-disk manifest layout to read or write.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Format {
+    /// `checksums`'s own layout.
+    Native,
+    /// `<hexdigest>␠␠<path>`, as written by `sha1sum`/`sha256sum`/`md5sum`.
+    Gnu,
+    /// `ALGO (path) = digest`, as written by the BSD `*sum` tools.
+    Bsd,
+}
+
+/// One parsed manifest entry: the path it covers, its recorded digest
+/// (lowercase hex), and -- for `Bsd` lines, which embed it -- the
+/// algorithm tag the line itself claimed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: String,
+    pub digest: String,
+    pub tagged_algorithm: Option<String>,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" => Ok(Format::Native),
+            "gnu" => Ok(Format::Gnu),
+            "bsd" => Ok(Format::Bsd),
+            _ => Err(format!("unrecognised manifest format {:?}, expected one of: native, gnu, bsd", s)),
+        }
+    }
+}
+
+impl Format {
+    /// Render one manifest line for `path`/`digest` in this format.
+    /// `algorithm` is only used -- as the leading tag -- by `Bsd`.
+    pub fn format_line(&self, algorithm: &str, path: &str, digest: &str) -> String {
+        match *self {
+            Format::Native => format!("{}  {}", digest, path),
+            Format::Gnu => format!("{}  {}", digest, path),
+            Format::Bsd => format!("{} ({}) = {}", algorithm, path, digest),
+        }
+    }
+
+    /// Parse one manifest line written in this format.
+    ///
+    /// GNU lines are `<digest> <mode><path>`, where `<mode>` is a single
+    /// byte: `' '` for text mode (giving the familiar two-space-looking
+    /// `digest  path`) or `'*'` for binary mode (`sha1sum --binary`),
+    /// e.g. `da39a3ee5e6b4b0d3255bfef95601890afd80709 *empty.bin`.
+    pub fn parse_line(&self, line: &str) -> Result<Entry, String> {
+        match *self {
+            Format::Native | Format::Gnu => {
+                let space = line.find(' ').ok_or_else(|| format!("malformed manifest line: {:?}", line))?;
+                let (digest, rest) = (&line[..space], &line[space + 1..]);
+                if rest.is_empty() || !(rest.starts_with('*') || rest.starts_with(' ')) {
+                    return Err(format!("malformed manifest line: {:?}", line));
+                }
+
+                Ok(Entry {
+                    path: rest[1..].to_string(),
+                    digest: digest.to_lowercase(),
+                    tagged_algorithm: None,
+                })
+            }
+            Format::Bsd => {
+                let open = line.find('(').ok_or_else(|| format!("malformed BSD manifest line: {:?}", line))?;
+                let close = line.find(')').ok_or_else(|| format!("malformed BSD manifest line: {:?}", line))?;
+                let eq = line.rfind('=').ok_or_else(|| format!("malformed BSD manifest line: {:?}", line))?;
+
+                if !(open < close && close < eq) {
+                    return Err(format!("malformed BSD manifest line: {:?}", line));
+                }
+
+                Ok(Entry {
+                    path: line[open + 1..close].to_string(),
+                    digest: line[eq + 1..].trim().to_lowercase(),
+                    tagged_algorithm: Some(line[..open].trim().to_string()),
+                })
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Format, Entry};
+
+    #[test]
+    fn gnu_roundtrip() {
+        let line = Format::Gnu.format_line("SHA1", "src/main.rs", "deadbeef");
+        assert_eq!(Format::Gnu.parse_line(&line).unwrap(),
+                   Entry {
+                       path: "src/main.rs".to_string(),
+                       digest: "deadbeef".to_string(),
+                       tagged_algorithm: None,
+                   });
+    }
+
+    #[test]
+    fn bsd_roundtrip() {
+        let line = Format::Bsd.format_line("SHA1", "src/main.rs", "deadbeef");
+        assert_eq!(Format::Bsd.parse_line(&line).unwrap(),
+                   Entry {
+                       path: "src/main.rs".to_string(),
+                       digest: "deadbeef".to_string(),
+                       tagged_algorithm: Some("SHA1".to_string()),
+                   });
+    }
+
+    #[test]
+    fn gnu_binary_mode_line_parses() {
+        // As written by `sha1sum --binary empty.bin` for an empty file:
+        // one space, then the `*` binary-mode marker directly against the path.
+        let entry = Format::Gnu.parse_line("da39a3ee5e6b4b0d3255bfef95601890afd80709 *empty.bin").unwrap();
+        assert_eq!(entry,
+                   Entry {
+                       path: "empty.bin".to_string(),
+                       digest: "da39a3ee5e6b4b0d3255bfef95601890afd80709".to_string(),
+                       tagged_algorithm: None,
+                   });
+    }
+
+    #[test]
+    fn gnu_text_mode_line_parses() {
+        // As written by plain `sha1sum empty.bin`: one space, then the
+        // text-mode marker (another space) directly against the path.
+        let entry = Format::Gnu.parse_line("da39a3ee5e6b4b0d3255bfef95601890afd80709  empty.bin").unwrap();
+        assert_eq!(entry.path, "empty.bin");
+    }
+
+    #[test]
+    fn from_str_rejects_unknown() {
+        "xml".parse::<Format>().unwrap_err();
+    }
+
+    #[test]
+    fn bsd_rejects_out_of_order_brackets() {
+        // The `=` has to come after the `)`, and the `)` after the `(`;
+        // anything else isn't a line this format ever writes.
+        Format::Bsd.parse_line(") = (").unwrap_err();
+    }
+}
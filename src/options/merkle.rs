@@ -0,0 +1,171 @@
+//! A fixed-arity (binary) Merkle commitment tree over a set of leaf
+//! hashes, plus compact per-leaf inclusion proofs.
+//!
+//! Leaves are the per-file digests in sorted-relative-path order. The
+//! tree is built bottom-up, level by level, by hashing adjacent sibling
+//! pairs together; a missing right sibling at a level is combined with
+//! a canonical "empty" padding node for that level instead of being
+//! promoted unhashed, so every level has a well-defined pair count.
+//!
+//! A proof for leaf `i` is its authentication path: the sibling hash at
+//! each level from the leaf up to the root, plus the leaf's position
+//! (needed to know, at each level, whether the leaf side is the left or
+//! right operand of the pairing hash).
+
+
+/// A completed Merkle tree: one vector of node hashes per level, leaves
+/// first (`levels[0]`) and the single root last.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+/// A compact inclusion proof for a single leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    /// Index of the proven leaf among the original (unpadded) leaves.
+    pub position: usize,
+    /// Sibling hash at each level, from the leaf's level up to the root.
+    pub siblings: Vec<Vec<u8>>,
+}
+
+
+impl MerkleTree {
+    /// Build a tree over `leaves` (already in their final, deterministic
+    /// order), using `hash_pair` to combine two child hashes into their
+    /// parent and `empty` as the canonical padding node for a level
+    /// whose right sibling is missing. `leaves` may be empty (an empty
+    /// directory, or one with no matching files); such a tree simply has
+    /// no root -- see `root()`.
+    pub fn build<H>(leaves: Vec<Vec<u8>>, empty: &[u8], hash_pair: &H) -> MerkleTree
+        where H: Fn(&[u8], &[u8]) -> Vec<u8>
+    {
+        let mut levels = vec![leaves];
+        let mut padding = empty.to_vec();
+
+        while levels.last().unwrap().len() > 1 {
+            let level = levels.last().unwrap();
+            let mut next = Vec::with_capacity((level.len() + 1) / 2);
+
+            let mut i = 0;
+            while i < level.len() {
+                let left = &level[i];
+                let right = level.get(i + 1).unwrap_or(&padding);
+                next.push(hash_pair(left, right));
+                i += 2;
+            }
+
+            padding = hash_pair(&padding, &padding);
+            levels.push(next);
+        }
+
+        MerkleTree { levels: levels }
+    }
+
+    /// The root hash of the tree, or `None` if it was built over zero
+    /// leaves (an empty directory, or one with no matching files).
+    pub fn root(&self) -> Option<&[u8]> {
+        self.levels.last().and_then(|level| level.first()).map(Vec::as_slice)
+    }
+
+    /// Number of levels between the leaves and the root, inclusive of
+    /// the leaf level.
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    /// Produce the inclusion proof for the leaf originally at `position`.
+    pub fn prove(&self, position: usize) -> Proof {
+        let mut siblings = Vec::with_capacity(self.depth());
+        let mut index = position;
+
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling_index = index ^ 1;
+            match level.get(sibling_index) {
+                Some(sibling) => siblings.push(sibling.clone()),
+                None => siblings.push(Vec::new()), // combined with padding when built
+            }
+            index /= 2;
+        }
+
+        Proof {
+            position: position,
+            siblings: siblings,
+        }
+    }
+}
+
+/// Recompute the root implied by `leaf` and `proof`, using that level's
+/// padding node (derived from `empty` exactly as `MerkleTree::build`
+/// does) for any sibling the proof recorded as missing, and compare it
+/// to `expected_root`.
+pub fn verify<H>(leaf: &[u8], proof: &Proof, empty: &[u8], expected_root: &[u8], hash_pair: &H) -> bool
+    where H: Fn(&[u8], &[u8]) -> Vec<u8>
+{
+    let mut hash = leaf.to_vec();
+    let mut index = proof.position;
+    let mut padding = empty.to_vec();
+
+    for sibling in &proof.siblings {
+        let sibling = if sibling.is_empty() { &padding[..] } else { &sibling[..] };
+        hash = if index % 2 == 0 { hash_pair(&hash, sibling) } else { hash_pair(sibling, &hash) };
+        padding = hash_pair(&padding, &padding);
+        index /= 2;
+    }
+
+    hash == expected_root
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{MerkleTree, verify};
+
+    fn hash_pair(l: &[u8], r: &[u8]) -> Vec<u8> {
+        let mut out = l.to_vec();
+        out.extend_from_slice(r);
+        out
+    }
+
+    #[test]
+    fn empty_leaves_has_no_root() {
+        let tree = MerkleTree::build(vec![], &[0], &hash_pair);
+        assert_eq!(tree.root(), None);
+    }
+
+    #[test]
+    fn single_leaf_root_is_itself() {
+        let tree = MerkleTree::build(vec![vec![1, 2, 3]], &[0], &hash_pair);
+        assert_eq!(tree.root(), Some(&[1, 2, 3][..]));
+    }
+
+    #[test]
+    fn odd_leaf_count_pads_with_empty() {
+        let leaves = vec![vec![1], vec![2], vec![3]];
+        let tree = MerkleTree::build(leaves, &[0], &hash_pair);
+
+        let expected_pair3 = hash_pair(&[3], &[0]);
+        let expected_root = hash_pair(&hash_pair(&[1], &[2]), &expected_pair3);
+        assert_eq!(tree.root(), Some(&expected_root[..]));
+    }
+
+    #[test]
+    fn proof_roundtrips_for_every_leaf() {
+        let leaves = vec![vec![1], vec![2], vec![3], vec![4], vec![5]];
+        let tree = MerkleTree::build(leaves.clone(), &[0], &hash_pair);
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = tree.prove(i);
+            assert!(verify(leaf, &proof, &[0], tree.root().unwrap(), &hash_pair));
+        }
+    }
+
+    #[test]
+    fn tampered_leaf_fails_verification() {
+        let leaves = vec![vec![1], vec![2], vec![3], vec![4]];
+        let tree = MerkleTree::build(leaves, &[0], &hash_pair);
+
+        let proof = tree.prove(0);
+        assert!(!verify(&[99], &proof, &[0], tree.root().unwrap(), &hash_pair));
+    }
+}
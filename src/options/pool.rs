@@ -0,0 +1,134 @@
+//! A bounded worker pool for hashing files concurrently.
+//!
+//! The caller (the directory walk) is the producer: `hash_all` takes an
+//! iterator of paths rather than a pre-collected list, spawns a
+//! dedicated producer thread that drains it into a bounded
+//! (`mpsc::sync_channel`) queue, and `N` worker threads each pull a
+//! path off that queue, hash it with the caller-supplied function, and
+//! send the result back over a second channel. Because the queue is
+//! bounded, the walk blocks once it's gotten far enough ahead of the
+//! hashers -- it overlaps directory-walking I/O with hashing instead of
+//! finishing the whole walk before any hashing starts, without letting
+//! an unbounded backlog of paths pile up in memory. `hash_all` collects
+//! the results and sorts them by path before returning them, so output
+//! is independent of which worker happened to finish first.
+//!
+//! `N == 1` runs the hashing inline on the calling thread instead of
+//! spawning anything, so single-job runs behave exactly as a
+//! sequential loop would -- deterministic ordering, no thread-pool or
+//! channel overhead.
+
+
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::path::PathBuf;
+use std::thread;
+
+
+/// Hash every path yielded by `paths` with `hash`, using up to `jobs`
+/// worker threads, and return `(path, digest)` pairs sorted by path.
+///
+/// `paths` is consumed by a producer thread running concurrently with
+/// the workers, through a channel bounded to `jobs * 2` entries, so a
+/// slow walk and slow hashing apply backpressure to each other rather
+/// than the whole tree being buffered in memory up front.
+pub fn hash_all<I, T, F>(paths: I, jobs: u32, hash: F) -> Vec<(PathBuf, T)>
+    where I: IntoIterator<Item = PathBuf> + Send,
+          F: Fn(&PathBuf) -> T + Send + Sync,
+          T: Send
+{
+    let mut results = if jobs <= 1 {
+        paths.into_iter().map(|p| { let digest = hash(&p); (p, digest) }).collect::<Vec<_>>()
+    } else {
+        run_pool(paths, jobs, &hash)
+    };
+
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    results
+}
+
+fn run_pool<I, T, F>(paths: I, jobs: u32, hash: &F) -> Vec<(PathBuf, T)>
+    where I: IntoIterator<Item = PathBuf> + Send,
+          F: Fn(&PathBuf) -> T + Send + Sync,
+          T: Send
+{
+    let (work_tx, work_rx) = mpsc::sync_channel::<PathBuf>(jobs as usize * 2);
+    let work_rx = Mutex::new(work_rx);
+    let (result_tx, result_rx) = mpsc::channel::<(PathBuf, T)>();
+
+    thread::scope(|scope| {
+        // Producer: feeds the bounded channel from the (possibly slow,
+        // I/O-bound) path source, concurrently with the workers below.
+        scope.spawn(move || {
+            for path in paths {
+                if work_tx.send(path).is_err() {
+                    break;
+                }
+            }
+        });
+
+        for _ in 0..jobs {
+            let work_rx = &work_rx;
+            let result_tx = result_tx.clone();
+            scope.spawn(move || {
+                loop {
+                    let path = match work_rx.lock().unwrap().recv() {
+                        Ok(path) => path,
+                        Err(_) => break,
+                    };
+                    let digest = hash(&path);
+                    result_tx.send((path, digest)).unwrap();
+                }
+            });
+        }
+        drop(result_tx);
+    });
+
+    collect(result_rx)
+}
+
+fn collect<T>(rx: Receiver<(PathBuf, T)>) -> Vec<(PathBuf, T)> {
+    rx.into_iter().collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::hash_all;
+    use std::path::PathBuf;
+
+    fn fake_hash(p: &PathBuf) -> usize {
+        p.to_string_lossy().len()
+    }
+
+    #[test]
+    fn single_job_matches_sequential() {
+        let paths = vec![PathBuf::from("b"), PathBuf::from("a"), PathBuf::from("c")];
+        let result = hash_all(paths, 1, fake_hash);
+        assert_eq!(result, vec![(PathBuf::from("a"), 1), (PathBuf::from("b"), 1), (PathBuf::from("c"), 1)]);
+    }
+
+    #[test]
+    fn multiple_jobs_still_sorted() {
+        let paths = vec![PathBuf::from("z"), PathBuf::from("m"), PathBuf::from("a"), PathBuf::from("q")];
+        let result = hash_all(paths, 4, fake_hash);
+        let sorted_paths: Vec<_> = result.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(sorted_paths, vec![PathBuf::from("a"), PathBuf::from("m"), PathBuf::from("q"), PathBuf::from("z")]);
+    }
+
+    #[test]
+    fn more_jobs_than_paths_still_completes() {
+        let paths = vec![PathBuf::from("only-one")];
+        let result = hash_all(paths, 8, fake_hash);
+        assert_eq!(result, vec![(PathBuf::from("only-one"), 8)]);
+    }
+
+    #[test]
+    fn lazy_iterator_source_is_fully_drained() {
+        // Exercises the producer-thread path with something that isn't
+        // already a materialized Vec, standing in for a directory walk.
+        let paths = (0..50).map(|i| PathBuf::from(format!("file-{}", i)));
+        let result = hash_all(paths, 4, fake_hash);
+        assert_eq!(result.len(), 50);
+    }
+}